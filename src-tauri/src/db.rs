@@ -1,145 +1,494 @@
+use crate::error::LunchError;
 use chrono::Utc;
+use r2d2::{CustomizeConnection, Pool, PooledConnection};
+use r2d2_sqlite::SqliteConnectionManager;
 use rand::prelude::IndexedRandom;
-use rusqlite::{Connection, Result};
+use rusqlite::Connection;
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
-use std::sync::Mutex;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+/// Result type for every fallible [`Database`] operation.
+type Result<T> = std::result::Result<T, LunchError>;
+
+/// How many recent picks are retained, and the default ceiling on the
+/// recency window used when weighting a roll.
+const DEFAULT_RECENT_WINDOW: usize = 14;
+
+type SqlitePool = Pool<SqliteConnectionManager>;
+
+/// Per-connection SQLite tuning applied to every connection the pool hands
+/// out, so settings survive checkout/checkin and apply uniformly under WAL.
+#[derive(Debug, Clone)]
+pub struct ConnectionOptions {
+    /// How long a write should wait on a locked database before giving up.
+    pub busy_timeout: Option<Duration>,
+    /// Enable write-ahead logging so readers don't block the writer.
+    pub enable_wal_mode: bool,
+    /// Enforce foreign-key constraints (off by default in SQLite).
+    pub enable_foreign_keys: bool,
+}
+
+impl Default for ConnectionOptions {
+    fn default() -> Self {
+        Self {
+            busy_timeout: Some(Duration::from_secs(5)),
+            enable_wal_mode: true,
+            enable_foreign_keys: true,
+        }
+    }
+}
+
+impl CustomizeConnection<Connection, rusqlite::Error> for ConnectionOptions {
+    fn on_acquire(&self, conn: &mut Connection) -> rusqlite::Result<()> {
+        if let Some(timeout) = self.busy_timeout {
+            conn.pragma_update(None, "busy_timeout", timeout.as_millis() as i64)?;
+        }
+        if self.enable_wal_mode {
+            conn.pragma_update(None, "journal_mode", "WAL")?;
+        }
+        if self.enable_foreign_keys {
+            conn.pragma_update(None, "foreign_keys", "ON")?;
+        }
+        Ok(())
+    }
+}
+
+/// Hands out a distinct shared-cache memory database per [`Database::in_memory`]
+/// so pooled connections see the same tables while separate instances stay
+/// isolated.
+static MEMDB_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// A single forward schema step, modeled on `rusqlite_migration`'s `M::up`.
+///
+/// Migrations are applied in the order they appear in [`MIGRATIONS`]; the
+/// number of applied entries is stored in the `user_version` PRAGMA so
+/// an existing `lunch.db` only ever runs the steps it is missing.
+struct M {
+    up: &'static str,
+}
+
+/// Ordered list of schema migrations. Never reorder or edit an existing entry
+/// once it has shipped — append a new one instead, otherwise already-migrated
+/// databases will skip the change.
+const MIGRATIONS: &[M] = &[
+    // v1: the original schema, with the legacy `restaurants`/`option` names.
+    M {
+        up: r#"
+            CREATE TABLE IF NOT EXISTS lunch_list (
+                restaurants TEXT PRIMARY KEY,
+                option TEXT
+            );
+            CREATE TABLE IF NOT EXISTS recent_lunch (
+                restaurants TEXT PRIMARY KEY,
+                date TEXT
+            );
+        "#,
+    },
+    // v2: give the columns honest names.
+    M {
+        up: r#"
+            ALTER TABLE lunch_list RENAME COLUMN restaurants TO name;
+            ALTER TABLE lunch_list RENAME COLUMN option TO category;
+            ALTER TABLE recent_lunch RENAME COLUMN restaurants TO name;
+        "#,
+    },
+    // v3: free-form entity-attribute table for tags like cuisine/distance.
+    M {
+        up: r#"
+            CREATE TABLE IF NOT EXISTS restaurant_attrs (
+                name TEXT NOT NULL,
+                attr TEXT NOT NULL,
+                value TEXT,
+                PRIMARY KEY (name, attr)
+            );
+        "#,
+    },
+];
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Default)]
 pub struct Restaurant {
     pub name: String,
     pub category: String,
+    /// Free-form tags (e.g. `cuisine=thai`, `distance=near`), populated on read.
+    #[serde(default)]
+    pub attributes: HashMap<String, String>,
+}
+
+/// How [`Database::import`] reacts to a row whose name collides with an
+/// existing one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OnConflict {
+    /// Abort the whole import and roll back — the safe default when restoring
+    /// a full backup.
+    Abort,
+    /// Skip the colliding row and carry on, keeping the existing entry.
+    Skip,
 }
 
 pub struct Database {
-    conn: Mutex<Connection>,
+    pool: SqlitePool,
+    /// Override for the recency window; `None` derives it per roll from the
+    /// candidate count (see [`Database::select_and_record`]).
+    recent_window: Option<usize>,
 }
 
 impl Database {
     pub fn new() -> Result<Self> {
+        Self::new_with_options(ConnectionOptions::default())
+    }
+
+    /// Open the on-disk `lunch.db` with the given connection tuning.
+    pub fn new_with_options(options: ConnectionOptions) -> Result<Self> {
         let path = get_db_path();
         if let Some(parent) = path.parent() {
             std::fs::create_dir_all(parent).ok();
         }
-        let conn = Connection::open(&path)?;
-        Self::with_connection(conn)
+        Self::open(path, options)
+    }
+
+    /// Open (or create) a database at `path` backed by a pool.
+    pub fn open<P: AsRef<Path>>(path: P, options: ConnectionOptions) -> Result<Self> {
+        let manager = SqliteConnectionManager::file(path);
+        Self::from_manager(manager, options)
+    }
+
+    pub fn in_memory() -> Result<Self> {
+        Self::in_memory_with_options(ConnectionOptions::default())
     }
 
-    pub fn with_connection(conn: Connection) -> Result<Self> {
+    /// Open an isolated shared-cache in-memory database with the given tuning.
+    pub fn in_memory_with_options(options: ConnectionOptions) -> Result<Self> {
+        let id = MEMDB_COUNTER.fetch_add(1, Ordering::Relaxed);
+        let manager = SqliteConnectionManager::file(format!(
+            "file:lunch_mem_{id}?mode=memory&cache=shared"
+        ));
+        Self::from_manager(manager, options)
+    }
+
+    fn from_manager(manager: SqliteConnectionManager, options: ConnectionOptions) -> Result<Self> {
+        let pool = Pool::builder()
+            .connection_customizer(Box::new(options))
+            .build(manager)?;
         let db = Database {
-            conn: Mutex::new(conn),
+            pool,
+            recent_window: None,
         };
-        db.init_tables()?;
+        db.migrate()?;
         Ok(db)
     }
 
-    pub fn in_memory() -> Result<Self> {
-        let conn = Connection::open_in_memory()?;
-        Self::with_connection(conn)
+    /// Fix the recency window to `window` picks instead of deriving it from
+    /// the candidate count on each roll.
+    pub fn with_recent_window(mut self, window: usize) -> Self {
+        self.recent_window = Some(window);
+        self
     }
 
-    fn init_tables(&self) -> Result<()> {
-        let conn = self.conn.lock().unwrap();
-        conn.execute_batch(
-            r#"
-            CREATE TABLE IF NOT EXISTS lunch_list (
-                restaurants TEXT PRIMARY KEY,
-                option TEXT
-            );
-            CREATE TABLE IF NOT EXISTS recent_lunch (
-                restaurants TEXT PRIMARY KEY,
-                date TEXT
-            );
-        "#,
-        )?;
+    fn conn(&self) -> Result<PooledConnection<SqliteConnectionManager>> {
+        Ok(self.pool.get()?)
+    }
+
+    /// Bring the connected database up to the latest schema version.
+    ///
+    /// Reads the current `user_version`, applies every migration after it in
+    /// ascending order — each inside its own transaction so a failure leaves
+    /// the file at the last good version — and records the new version.
+    fn migrate(&self) -> Result<()> {
+        let mut conn = self.conn()?;
+        let current: usize =
+            conn.query_row("PRAGMA user_version", [], |row| row.get::<_, i64>(0))? as usize;
+
+        for (idx, migration) in MIGRATIONS.iter().enumerate().skip(current) {
+            let version = idx + 1;
+            let tx = conn.transaction()?;
+            tx.execute_batch(migration.up)?;
+            // PRAGMA user_version does not accept bound parameters.
+            tx.execute_batch(&format!("PRAGMA user_version = {version};"))?;
+            tx.commit()?;
+        }
         Ok(())
     }
 
     pub fn list_all(&self) -> Result<Vec<Restaurant>> {
-        let conn = self.conn.lock().unwrap();
-        let mut stmt = conn.prepare("SELECT restaurants, option FROM lunch_list ORDER BY restaurants")?;
-        let rows = stmt.query_map([], |row| {
-            Ok(Restaurant {
-                name: row.get(0)?,
-                category: row.get(1)?,
-            })
-        })?;
-        rows.collect()
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare("SELECT name, category FROM lunch_list ORDER BY name")?;
+        let rows = stmt
+            .query_map([], row_to_restaurant)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        hydrate(&conn, rows)
     }
 
     pub fn list_by_category(&self, category: &str) -> Result<Vec<Restaurant>> {
-        let conn = self.conn.lock().unwrap();
+        let conn = self.conn()?;
         let mut stmt = conn
-            .prepare("SELECT restaurants, option FROM lunch_list WHERE LOWER(option) = LOWER(?)")?;
-        let rows = stmt.query_map([category], |row| {
-            Ok(Restaurant {
-                name: row.get(0)?,
-                category: row.get(1)?,
-            })
-        })?;
-        rows.collect()
+            .prepare("SELECT name, category FROM lunch_list WHERE LOWER(category) = LOWER(?)")?;
+        let rows = stmt
+            .query_map([category], row_to_restaurant)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        hydrate(&conn, rows)
     }
 
     pub fn add(&self, name: &str, category: &str) -> Result<()> {
-        let conn = self.conn.lock().unwrap();
+        let conn = self.conn()?;
         conn.execute(
-            "INSERT INTO lunch_list (restaurants, option) VALUES (?, ?)",
+            "INSERT INTO lunch_list (name, category) VALUES (?, ?)",
             [name, category],
-        )?;
+        )
+        .map_err(|e| match e {
+            rusqlite::Error::SqliteFailure(err, _)
+                if err.code == rusqlite::ErrorCode::ConstraintViolation =>
+            {
+                LunchError::DuplicateRestaurant(name.to_string())
+            }
+            other => LunchError::Db(other),
+        })?;
         Ok(())
     }
 
     pub fn delete(&self, name: &str) -> Result<()> {
-        let conn = self.conn.lock().unwrap();
-        conn.execute("DELETE FROM lunch_list WHERE restaurants = ?", [name])?;
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+        tx.execute("DELETE FROM lunch_list WHERE name = ?", [name])?;
+        tx.execute("DELETE FROM restaurant_attrs WHERE name = ?", [name])?;
+        tx.commit()?;
         Ok(())
     }
 
     pub fn roll(&self, category: &str) -> Result<Restaurant> {
         let restaurants = self.list_by_category(category)?;
         if restaurants.is_empty() {
-            return Err(rusqlite::Error::QueryReturnedNoRows);
+            return Err(LunchError::EmptyCategory(category.to_string()));
+        }
+        self.select_and_record(&restaurants)
+    }
+
+    /// Roll among restaurants matching *all* of the given attribute pairs.
+    ///
+    /// Candidates are restricted to rows that carry every `(attr, value)`
+    /// filter, then the same recent-exclusion logic as [`roll`](Self::roll)
+    /// picks one. An empty filter set rolls across the whole list.
+    pub fn roll_filtered(&self, filters: &[(String, String)]) -> Result<Restaurant> {
+        let restaurants = self.list_by_attrs(filters)?;
+        if restaurants.is_empty() {
+            return Err(LunchError::NoMatch(filters.to_vec()));
+        }
+        self.select_and_record(&restaurants)
+    }
+
+    /// Set (or overwrite) a single attribute on a restaurant.
+    pub fn set_attr(&self, name: &str, attr: &str, value: &str) -> Result<()> {
+        let conn = self.conn()?;
+        conn.execute(
+            "INSERT OR REPLACE INTO restaurant_attrs (name, attr, value) VALUES (?, ?, ?)",
+            [name, attr, value],
+        )?;
+        Ok(())
+    }
+
+    /// Fetch every attribute recorded for a restaurant.
+    pub fn get_attrs(&self, name: &str) -> Result<HashMap<String, String>> {
+        let conn = self.conn()?;
+        load_attrs(&conn, name)
+    }
+
+    /// Restaurants carrying every `(attr, value)` pair in `filters`.
+    fn list_by_attrs(&self, filters: &[(String, String)]) -> Result<Vec<Restaurant>> {
+        if filters.is_empty() {
+            return self.list_all();
+        }
+
+        let conn = self.conn()?;
+        let placeholders = vec!["(?, ?)"; filters.len()].join(", ");
+        let sql = format!(
+            "SELECT l.name, l.category FROM lunch_list l \
+             JOIN restaurant_attrs a ON a.name = l.name \
+             WHERE (a.attr, a.value) IN ({placeholders}) \
+             GROUP BY l.name, l.category \
+             HAVING COUNT(DISTINCT a.attr) = ? \
+             ORDER BY l.name"
+        );
+
+        let mut params: Vec<&dyn rusqlite::ToSql> = Vec::with_capacity(filters.len() * 2 + 1);
+        for (attr, value) in filters {
+            params.push(attr);
+            params.push(value);
+        }
+        let count = filters.len() as i64;
+        params.push(&count);
+
+        let mut stmt = conn.prepare(&sql)?;
+        let rows = stmt
+            .query_map(params.as_slice(), row_to_restaurant)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        hydrate(&conn, rows)
+    }
+
+    /// Recency-weighted selection: candidates that haven't been picked in a
+    /// while (or ever) are favoured, the immediately previous pick is excluded
+    /// whenever another option exists, and the choice is recorded in
+    /// `recent_lunch`.
+    fn select_and_record(&self, restaurants: &[Restaurant]) -> Result<Restaurant> {
+        // A single option always repeats — nothing else to pick.
+        if restaurants.len() == 1 {
+            return self.record_choice(&restaurants[0]);
         }
 
-        // Get last selected to avoid immediate repeat
-        let conn = self.conn.lock().unwrap();
-        let last: Option<String> = conn
-            .query_row(
-                "SELECT restaurants FROM recent_lunch ORDER BY date DESC LIMIT 1",
-                [],
-                |row| row.get(0),
-            )
-            .ok();
-
-        // Filter out last selection if possible
-        let available: Vec<_> = restaurants
-            .iter()
-            .filter(|r| Some(&r.name) != last.as_ref())
-            .cloned()
-            .collect();
-
-        let chosen = if available.is_empty() {
-            restaurants.choose(&mut rand::rng()).unwrap()
-        } else {
-            available.choose(&mut rand::rng()).unwrap()
+        let conn = self.conn()?;
+        let window = self
+            .recent_window
+            .unwrap_or_else(|| restaurants.len().saturating_sub(1).min(DEFAULT_RECENT_WINDOW));
+
+        // Most-recent pick first, out to the configured window. At least the
+        // previous pick is always read so it can be excluded below.
+        let recent: Vec<String> = {
+            let mut stmt =
+                conn.prepare("SELECT name FROM recent_lunch ORDER BY date DESC LIMIT ?")?;
+            stmt.query_map([window.max(1) as i64], |row| row.get(0))?
+                .collect::<rusqlite::Result<Vec<_>>>()?
         };
 
-        // Record selection
+        // Weight grows with staleness: the longer since a restaurant was last
+        // chosen, the heavier it is; never-picked restaurants get the maximum.
+        // The immediately previous pick is pinned to zero so it can't repeat
+        // while another option is available.
+        let max_weight = (window + 1) as u32;
+        let weight_of = |r: &Restaurant| -> u32 {
+            match recent.iter().position(|n| n == &r.name) {
+                Some(0) => 0,
+                Some(pos) => pos as u32,
+                None => max_weight,
+            }
+        };
+
+        let chosen = restaurants
+            .choose_weighted(&mut rand::rng(), weight_of)
+            .expect("weighted choice over a non-empty candidate pool")
+            .clone();
+
+        drop(conn);
+        self.record_choice(&chosen)
+    }
+
+    /// Persist `chosen` as the latest pick and trim the recency table.
+    fn record_choice(&self, chosen: &Restaurant) -> Result<Restaurant> {
+        let conn = self.conn()?;
         conn.execute(
-            "INSERT OR REPLACE INTO recent_lunch (restaurants, date) VALUES (?, ?)",
+            "INSERT OR REPLACE INTO recent_lunch (name, date) VALUES (?, ?)",
             [&chosen.name, &Utc::now().to_rfc3339()],
         )?;
 
-        // Keep only last 14 entries
+        // Keep only the most recent window of picks.
         conn.execute(
-            "DELETE FROM recent_lunch WHERE restaurants NOT IN (
-                SELECT restaurants FROM recent_lunch ORDER BY date DESC LIMIT 14
+            "DELETE FROM recent_lunch WHERE name NOT IN (
+                SELECT name FROM recent_lunch ORDER BY date DESC LIMIT ?
             )",
-            [],
+            [DEFAULT_RECENT_WINDOW as i64],
         )?;
 
         Ok(chosen.clone())
     }
+
+    /// Snapshot the full restaurant list for backup, sorted by name.
+    pub fn export(&self) -> Result<Vec<Restaurant>> {
+        self.list_all()
+    }
+
+    /// Restore a list of restaurants, with their attributes, in a single
+    /// atomic transaction.
+    ///
+    /// Each restaurant and its tags are inserted through prepared statements;
+    /// if anything fails the transaction is dropped without commit, so a
+    /// partial import never leaves the tables half-populated. When `replace`
+    /// is set both `lunch_list` and `restaurant_attrs` are truncated first,
+    /// within the same transaction, so restored rows never inherit a previous
+    /// occupant's tags. The `on_conflict` mode decides whether a duplicate
+    /// name aborts the whole import or is quietly skipped — a skipped row
+    /// leaves the existing entry (and its attributes) untouched. Returns the
+    /// number of rows inserted.
+    pub fn import(
+        &self,
+        entries: &[Restaurant],
+        replace: bool,
+        on_conflict: OnConflict,
+    ) -> Result<usize> {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+
+        if replace {
+            tx.execute("DELETE FROM lunch_list", [])?;
+            tx.execute("DELETE FROM restaurant_attrs", [])?;
+        }
+
+        let sql = match on_conflict {
+            OnConflict::Abort => "INSERT INTO lunch_list (name, category) VALUES (?, ?)",
+            OnConflict::Skip => "INSERT OR IGNORE INTO lunch_list (name, category) VALUES (?, ?)",
+        };
+
+        let mut inserted = 0;
+        {
+            let mut stmt = tx.prepare(sql)?;
+            let mut attr_stmt =
+                tx.prepare("INSERT OR REPLACE INTO restaurant_attrs (name, attr, value) VALUES (?, ?, ?)")?;
+            for entry in entries {
+                let rows = stmt
+                    .execute([&entry.name, &entry.category])
+                    .map_err(|e| match e {
+                        rusqlite::Error::SqliteFailure(err, _)
+                            if err.code == rusqlite::ErrorCode::ConstraintViolation =>
+                        {
+                            LunchError::DuplicateRestaurant(entry.name.clone())
+                        }
+                        other => LunchError::Db(other),
+                    })?;
+                // Only carry tags for rows we actually inserted; a skipped
+                // duplicate keeps whatever attributes it already had.
+                if rows > 0 {
+                    for (attr, value) in &entry.attributes {
+                        attr_stmt.execute([&entry.name, attr, value])?;
+                    }
+                }
+                inserted += rows;
+            }
+        }
+
+        tx.commit()?;
+        Ok(inserted)
+    }
+}
+
+/// Map a `(name, category)` row onto a `Restaurant` with empty attributes;
+/// [`hydrate`] fills the attributes in a second pass.
+fn row_to_restaurant(row: &rusqlite::Row) -> rusqlite::Result<Restaurant> {
+    Ok(Restaurant {
+        name: row.get(0)?,
+        category: row.get(1)?,
+        attributes: HashMap::new(),
+    })
+}
+
+/// Load the attribute map for a single restaurant.
+fn load_attrs(conn: &Connection, name: &str) -> Result<HashMap<String, String>> {
+    let mut stmt = conn.prepare("SELECT attr, value FROM restaurant_attrs WHERE name = ?")?;
+    let rows = stmt.query_map([name], |row| {
+        Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+    })?;
+    let mut attrs = HashMap::new();
+    for row in rows {
+        let (attr, value) = row?;
+        attrs.insert(attr, value);
+    }
+    Ok(attrs)
+}
+
+/// Populate each restaurant's `attributes` map from `restaurant_attrs`.
+fn hydrate(conn: &Connection, mut restaurants: Vec<Restaurant>) -> Result<Vec<Restaurant>> {
+    for restaurant in &mut restaurants {
+        restaurant.attributes = load_attrs(conn, &restaurant.name)?;
+    }
+    Ok(restaurants)
 }
 
 fn get_db_path() -> PathBuf {
@@ -0,0 +1,58 @@
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+use thiserror::Error;
+
+/// Errors surfaced by the [`Database`](crate::db::Database) layer.
+///
+/// Conditions that used to be detected by substring-matching rusqlite's
+/// message text are recognised at the source and mapped onto these variants,
+/// so both the Tauri commands and the frontend can branch on a stable shape
+/// rather than a localized string.
+#[derive(Debug, Error)]
+pub enum LunchError {
+    #[error("Restaurant '{0}' already exists")]
+    DuplicateRestaurant(String),
+
+    #[error("No restaurants found in category '{0}'")]
+    EmptyCategory(String),
+
+    #[error("No restaurants match the given filters")]
+    NoMatch(Vec<(String, String)>),
+
+    #[error("Restaurant '{0}' not found")]
+    NotFound(String),
+
+    #[error(transparent)]
+    Db(#[from] rusqlite::Error),
+
+    #[error(transparent)]
+    Pool(#[from] r2d2::Error),
+}
+
+impl LunchError {
+    /// Stable, machine-readable code the frontend can match on regardless of
+    /// the human-facing message.
+    pub fn code(&self) -> &'static str {
+        match self {
+            LunchError::DuplicateRestaurant(_) => "DUPLICATE_RESTAURANT",
+            LunchError::EmptyCategory(_) => "EMPTY_CATEGORY",
+            LunchError::NoMatch(_) => "NO_MATCH",
+            LunchError::NotFound(_) => "NOT_FOUND",
+            LunchError::Db(_) => "DB_ERROR",
+            LunchError::Pool(_) => "POOL_ERROR",
+        }
+    }
+}
+
+/// Serialize as `{ "code": ..., "message": ... }` so the Tauri frontend can
+/// switch on `code` while still having a message to display.
+impl Serialize for LunchError {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut state = serializer.serialize_struct("LunchError", 2)?;
+        state.serialize_field("code", self.code())?;
+        state.serialize_field("message", &self.to_string())?;
+        state.end()
+    }
+}
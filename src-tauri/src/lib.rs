@@ -1,6 +1,8 @@
 pub mod db;
+pub mod error;
 
-use db::{Database, Restaurant};
+use db::{Database, OnConflict, Restaurant};
+use error::LunchError;
 use std::sync::OnceLock;
 
 static DB: OnceLock<Database> = OnceLock::new();
@@ -10,35 +12,57 @@ fn get_db() -> &'static Database {
 }
 
 #[tauri::command]
-fn list_restaurants() -> Result<Vec<Restaurant>, String> {
-    get_db().list_all().map_err(|e| e.to_string())
+fn list_restaurants() -> Result<Vec<Restaurant>, LunchError> {
+    get_db().list_all()
 }
 
 #[tauri::command]
-fn add_restaurant(name: String, category: String) -> Result<(), String> {
-    get_db().add(&name, &category).map_err(|e| {
-        if e.to_string().contains("UNIQUE constraint") {
-            format!("Restaurant '{}' already exists", name)
-        } else {
-            e.to_string()
-        }
-    })
+fn add_restaurant(name: String, category: String) -> Result<(), LunchError> {
+    get_db().add(&name, &category)
 }
 
 #[tauri::command]
-fn delete_restaurant(name: String) -> Result<(), String> {
-    get_db().delete(&name).map_err(|e| e.to_string())
+fn delete_restaurant(name: String) -> Result<(), LunchError> {
+    get_db().delete(&name)
 }
 
 #[tauri::command]
-fn roll_lunch(category: String) -> Result<Restaurant, String> {
-    get_db().roll(&category).map_err(|e| {
-        if e.to_string().contains("no rows") {
-            "No restaurants found!".to_string()
-        } else {
-            e.to_string()
-        }
-    })
+fn roll_lunch(category: String) -> Result<Restaurant, LunchError> {
+    get_db().roll(&category)
+}
+
+#[tauri::command]
+fn roll_filtered(filters: Vec<(String, String)>) -> Result<Restaurant, LunchError> {
+    get_db().roll_filtered(&filters)
+}
+
+#[tauri::command]
+fn set_attribute(name: String, attr: String, value: String) -> Result<(), LunchError> {
+    get_db().set_attr(&name, &attr, &value)
+}
+
+#[tauri::command]
+fn get_attributes(name: String) -> Result<std::collections::HashMap<String, String>, LunchError> {
+    get_db().get_attrs(&name)
+}
+
+#[tauri::command]
+fn export_restaurants() -> Result<Vec<Restaurant>, LunchError> {
+    get_db().export()
+}
+
+#[tauri::command]
+fn import_restaurants(
+    entries: Vec<Restaurant>,
+    replace: bool,
+    skip_duplicates: bool,
+) -> Result<usize, LunchError> {
+    let on_conflict = if skip_duplicates {
+        OnConflict::Skip
+    } else {
+        OnConflict::Abort
+    };
+    get_db().import(&entries, replace, on_conflict)
 }
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
@@ -50,6 +74,11 @@ pub fn run() {
             add_restaurant,
             delete_restaurant,
             roll_lunch,
+            roll_filtered,
+            set_attribute,
+            get_attributes,
+            export_restaurants,
+            import_restaurants,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
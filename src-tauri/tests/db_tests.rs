@@ -1,9 +1,94 @@
-use lunch::db::Database;
+use lunch::db::{ConnectionOptions, Database, OnConflict, Restaurant};
+use lunch::error::LunchError;
+use rusqlite::Connection;
+use std::sync::Arc;
+use std::thread;
 
 fn test_db() -> Database {
     Database::in_memory().expect("Failed to create in-memory database")
 }
 
+/// Seed a file with the original v1 schema (legacy column names,
+/// `user_version = 1`) and one row, as an old `lunch.db` on disk would look.
+fn seed_legacy_db(path: &std::path::Path) {
+    let conn = Connection::open(path).unwrap();
+    conn.execute_batch(
+        r#"
+        CREATE TABLE lunch_list (
+            restaurants TEXT PRIMARY KEY,
+            option TEXT
+        );
+        CREATE TABLE recent_lunch (
+            restaurants TEXT PRIMARY KEY,
+            date TEXT
+        );
+        INSERT INTO lunch_list (restaurants, option) VALUES ('Legacy Diner', 'cheap');
+        PRAGMA user_version = 1;
+        "#,
+    )
+    .unwrap();
+}
+
+#[test]
+fn test_legacy_schema_upgrades_without_data_loss() {
+    let dir = std::env::temp_dir().join(format!("lunch_legacy_{}.db", std::process::id()));
+    let _ = std::fs::remove_file(&dir);
+    seed_legacy_db(&dir);
+
+    let db = Database::open(&dir, ConnectionOptions::default()).unwrap();
+
+    let restaurants = db.list_all().unwrap();
+    assert_eq!(restaurants.len(), 1);
+    assert_eq!(restaurants[0].name, "Legacy Diner");
+    assert_eq!(restaurants[0].category, "cheap");
+
+    // The renamed schema is fully usable after the upgrade.
+    db.add("New Spot", "normal").unwrap();
+    assert_eq!(db.list_all().unwrap().len(), 2);
+
+    drop(db);
+    let _ = std::fs::remove_file(&dir);
+}
+
+#[test]
+fn test_concurrent_rolls_do_not_lock() {
+    // Use a file-backed database: busy_timeout only governs file locks, not
+    // the table-level SQLITE_LOCKED raised under shared-cache in-memory mode,
+    // so this exercises the path the WAL + busy_timeout tuning actually covers.
+    let path = std::env::temp_dir().join(format!("lunch_concurrent_{}.db", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+
+    let db = Arc::new(Database::open(&path, ConnectionOptions::default()).unwrap());
+    for i in 0..8 {
+        db.add(&format!("Place {i}"), "cheap").unwrap();
+    }
+
+    let handles: Vec<_> = (0..8)
+        .map(|_| {
+            let db = Arc::clone(&db);
+            thread::spawn(move || {
+                for _ in 0..20 {
+                    db.roll("cheap").expect("roll should not hit a locked database");
+                }
+            })
+        })
+        .collect();
+
+    for handle in handles {
+        handle.join().unwrap();
+    }
+
+    drop(db);
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn test_fresh_database_is_at_latest_schema() {
+    let db = test_db();
+    db.add("Place", "cheap").unwrap();
+    assert_eq!(db.list_by_category("cheap").unwrap().len(), 1);
+}
+
 #[test]
 fn test_database_initialization() {
     let db = test_db();
@@ -28,8 +113,10 @@ fn test_add_duplicate_restaurant() {
     db.add("Duplicate", "cheap").unwrap();
 
     let result = db.add("Duplicate", "normal");
-    assert!(result.is_err());
-    assert!(result.unwrap_err().to_string().contains("UNIQUE"));
+    assert!(matches!(
+        result,
+        Err(LunchError::DuplicateRestaurant(ref n)) if n == "Duplicate"
+    ));
 }
 
 #[test]
@@ -174,3 +261,209 @@ fn test_full_crud_workflow() {
     db.delete("Updated Restaurant").unwrap();
     assert!(db.list_all().unwrap().is_empty());
 }
+
+#[test]
+fn test_export_import_round_trip() {
+    let db = test_db();
+    db.add("Place A", "cheap").unwrap();
+    db.add("Place B", "normal").unwrap();
+    db.add("Place C", "cheap").unwrap();
+
+    let exported = db.export().unwrap();
+
+    let fresh = test_db();
+    let inserted = fresh.import(&exported, true, OnConflict::Abort).unwrap();
+    assert_eq!(inserted, 3);
+    assert_eq!(fresh.export().unwrap(), exported);
+}
+
+#[test]
+fn test_import_rolls_back_on_duplicate() {
+    let db = test_db();
+    db.add("Existing", "cheap").unwrap();
+
+    let entries = vec![
+        Restaurant { name: "Brand New".into(), category: "normal".into(), ..Default::default() },
+        Restaurant { name: "Existing".into(), category: "cheap".into(), ..Default::default() },
+    ];
+
+    let result = db.import(&entries, false, OnConflict::Abort);
+    assert!(matches!(result, Err(LunchError::DuplicateRestaurant(_))));
+
+    // The whole import rolled back: the non-duplicate row must not survive.
+    let names: Vec<_> = db.list_all().unwrap().into_iter().map(|r| r.name).collect();
+    assert_eq!(names, vec!["Existing".to_string()]);
+}
+
+#[test]
+fn test_import_skip_duplicates() {
+    let db = test_db();
+    db.add("Existing", "cheap").unwrap();
+
+    let entries = vec![
+        Restaurant { name: "Brand New".into(), category: "normal".into(), ..Default::default() },
+        Restaurant { name: "Existing".into(), category: "cheap".into(), ..Default::default() },
+    ];
+
+    let inserted = db.import(&entries, false, OnConflict::Skip).unwrap();
+    assert_eq!(inserted, 1);
+    assert_eq!(db.list_all().unwrap().len(), 2);
+}
+
+#[test]
+fn test_set_and_get_attrs() {
+    let db = test_db();
+    db.add("Thai Place", "normal").unwrap();
+    db.set_attr("Thai Place", "cuisine", "thai").unwrap();
+    db.set_attr("Thai Place", "distance", "near").unwrap();
+
+    let attrs = db.get_attrs("Thai Place").unwrap();
+    assert_eq!(attrs.get("cuisine").map(String::as_str), Some("thai"));
+    assert_eq!(attrs.get("distance").map(String::as_str), Some("near"));
+}
+
+#[test]
+fn test_attributes_populated_on_read() {
+    let db = test_db();
+    db.add("Thai Place", "normal").unwrap();
+    db.set_attr("Thai Place", "cuisine", "thai").unwrap();
+
+    let listed = db.list_all().unwrap();
+    assert_eq!(listed[0].attributes.get("cuisine").map(String::as_str), Some("thai"));
+}
+
+#[test]
+fn test_roll_filtered_restricts_to_matching_attrs() {
+    let db = test_db();
+    db.add("Thai Near", "normal").unwrap();
+    db.add("Thai Far", "normal").unwrap();
+    db.add("Italian Near", "normal").unwrap();
+
+    db.set_attr("Thai Near", "cuisine", "thai").unwrap();
+    db.set_attr("Thai Near", "distance", "near").unwrap();
+    db.set_attr("Thai Far", "cuisine", "thai").unwrap();
+    db.set_attr("Thai Far", "distance", "far").unwrap();
+    db.set_attr("Italian Near", "cuisine", "italian").unwrap();
+    db.set_attr("Italian Near", "distance", "near").unwrap();
+
+    let filters = vec![
+        ("cuisine".to_string(), "thai".to_string()),
+        ("distance".to_string(), "near".to_string()),
+    ];
+    for _ in 0..10 {
+        assert_eq!(db.roll_filtered(&filters).unwrap().name, "Thai Near");
+    }
+}
+
+#[test]
+fn test_roll_filtered_no_match_errors() {
+    let db = test_db();
+    db.add("Thai Place", "normal").unwrap();
+    db.set_attr("Thai Place", "cuisine", "thai").unwrap();
+
+    let filters = vec![("cuisine".to_string(), "sushi".to_string())];
+    assert!(matches!(
+        db.roll_filtered(&filters),
+        Err(LunchError::NoMatch(_))
+    ));
+}
+
+#[test]
+fn test_delete_clears_attributes() {
+    let db = test_db();
+    db.add("Thai Place", "normal").unwrap();
+    db.set_attr("Thai Place", "cuisine", "thai").unwrap();
+
+    db.delete("Thai Place").unwrap();
+    assert!(db.get_attrs("Thai Place").unwrap().is_empty());
+
+    // Re-adding the same name must not resurrect the old tags.
+    db.add("Thai Place", "normal").unwrap();
+    assert!(db.list_all().unwrap()[0].attributes.is_empty());
+}
+
+#[test]
+fn test_export_import_round_trip_with_attributes() {
+    let db = test_db();
+    db.add("Thai Place", "normal").unwrap();
+    db.set_attr("Thai Place", "cuisine", "thai").unwrap();
+    db.set_attr("Thai Place", "distance", "near").unwrap();
+
+    let exported = db.export().unwrap();
+    assert_eq!(exported[0].attributes.len(), 2);
+
+    let fresh = test_db();
+    fresh.import(&exported, true, OnConflict::Abort).unwrap();
+    assert_eq!(fresh.export().unwrap(), exported);
+}
+
+#[test]
+fn test_roll_never_immediately_repeats_with_many_options() {
+    let db = test_db();
+    for name in ["Alpha", "Bravo", "Charlie", "Delta"] {
+        db.add(name, "cheap").unwrap();
+    }
+
+    let mut previous = db.roll("cheap").unwrap().name;
+    for _ in 0..200 {
+        let next = db.roll("cheap").unwrap().name;
+        assert_ne!(previous, next, "a roll must never immediately repeat");
+        previous = next;
+    }
+}
+
+#[test]
+fn test_roll_favors_long_unseen() {
+    let db = test_db();
+    let names = ["Alpha", "Bravo", "Charlie"];
+    for name in names {
+        db.add(name, "cheap").unwrap();
+    }
+
+    let mut counts = std::collections::HashMap::new();
+    let mut history: Vec<String> = Vec::new();
+    let mut stale_hits = 0;
+    let mut stale_chances = 0;
+    let rolls = 600;
+    for _ in 0..rolls {
+        let pick = db.roll("cheap").unwrap().name;
+        if history.len() >= 2 {
+            // The one name missing from the last two picks is the longest unseen.
+            let last_two = &history[history.len() - 2..];
+            let stale = names.iter().find(|n| !last_two.iter().any(|h| h == *n)).unwrap();
+            stale_chances += 1;
+            if pick == *stale {
+                stale_hits += 1;
+            }
+        }
+        history.push(pick.clone());
+        *counts.entry(pick).or_insert(0) += 1;
+    }
+
+    // The longest-unseen restaurant should win most of the time.
+    assert!(
+        stale_hits * 2 > stale_chances,
+        "longest-unseen picked only {stale_hits}/{stale_chances} times"
+    );
+
+    // Recency weighting should keep the rotation healthy: every restaurant
+    // turns up regularly rather than a couple monopolising the picks.
+    for name in names {
+        let count = *counts.get(name).unwrap_or(&0);
+        assert!(
+            count > rolls / 6,
+            "{name} picked only {count}/{rolls} times — distribution is too skewed"
+        );
+    }
+}
+
+#[test]
+fn test_roll_respects_configured_recent_window() {
+    let db = test_db().with_recent_window(1);
+    db.add("Alpha", "cheap").unwrap();
+    db.add("Bravo", "cheap").unwrap();
+
+    let first = db.roll("cheap").unwrap().name;
+    let second = db.roll("cheap").unwrap().name;
+    assert_ne!(first, second);
+}